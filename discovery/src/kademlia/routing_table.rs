@@ -1,15 +1,43 @@
 use std::cmp;
-use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
 
 use cnetwork::SocketAddr;
+use rand::seq::SliceRandom;
+use rand::thread_rng;
 
 use super::contact::Contact;
 use super::NodeId;
 
+/// Default per-bucket and per-table caps on contacts sharing the same IPv4 /24 or IPv6 /64,
+/// following karyon's defense against single-subnet eclipse attacks.
+const DEFAULT_MAX_MATCHED_SUBNET_IN_BUCKET: usize = 1;
+const DEFAULT_MAX_MATCHED_SUBNET_IN_TABLE: usize = 6;
+
+/// Outcome of `RoutingTable::touch_contact`, letting callers react per case (schedule a ping on
+/// `BucketFull`, log `Restricted`, ...) instead of inferring meaning from a bare `Option`.
+#[derive(Debug, Eq, PartialEq)]
+pub enum AddEntryResult {
+    /// The contact was inserted into its bucket.
+    Added,
+    /// An identical contact (same id and address) was already present.
+    Exists,
+    /// The contact was the local node itself, so there's nothing to add.
+    Ignored,
+    /// The contact was rejected by the conflict or subnet-diversity policy.
+    Restricted,
+    /// The bucket is already at capacity; the wrapped `Contact` is the best eviction candidate,
+    /// so the caller should ping it before deciding whether to replace it.
+    BucketFull(Contact),
+}
+
 pub struct RoutingTable {
     local_id: NodeId,
     buckets: HashMap<usize, Bucket>,
     bucket_size: u8,
+    max_matched_subnet_in_bucket: usize,
+    max_matched_subnet_in_table: usize,
 }
 
 impl RoutingTable {
@@ -19,23 +47,75 @@ impl RoutingTable {
             local_id,
             buckets: HashMap::with_capacity(CAPACITY),
             bucket_size,
+            max_matched_subnet_in_bucket: DEFAULT_MAX_MATCHED_SUBNET_IN_BUCKET,
+            max_matched_subnet_in_table: DEFAULT_MAX_MATCHED_SUBNET_IN_TABLE,
         }
     }
 
+    /// Overrides the subnet-diversity caps applied by `touch_contact`. Defaults to
+    /// `DEFAULT_MAX_MATCHED_SUBNET_IN_BUCKET` / `DEFAULT_MAX_MATCHED_SUBNET_IN_TABLE`.
+    #[allow(dead_code)]
+    pub fn set_subnet_limits(&mut self, max_matched_subnet_in_bucket: usize, max_matched_subnet_in_table: usize) {
+        self.max_matched_subnet_in_bucket = max_matched_subnet_in_bucket;
+        self.max_matched_subnet_in_table = max_matched_subnet_in_table;
+    }
+
     pub fn local_id(&self) -> NodeId {
         self.local_id
     }
 
-    pub fn touch_contact(&mut self, contact: Contact) -> Option<&Contact> {
+    pub fn touch_contact(&mut self, contact: Contact) -> AddEntryResult {
         let index = contact.log2_distance(&self.local_id);
         // FIXME: Decide the maximum distance to contact.
         if index == 0 {
-            return None
+            return AddEntryResult::Ignored
+        }
+        if !self.contains(&contact) && self.exceeds_subnet_limit(&contact, index) {
+            return AddEntryResult::Restricted
         }
         let bucket = self.add_bucket(index);
         bucket.touch_contact(contact)
     }
 
+    /// True if admitting `contact` into bucket `index` would push either subnet cap over its
+    /// limit, in which case `touch_contact` should reject the candidate outright.
+    fn exceeds_subnet_limit(&self, contact: &Contact, index: usize) -> bool {
+        let addr = contact.addr();
+        let in_bucket = self.buckets.get(&index).map(|bucket| bucket.count_subnet_matches(addr)).unwrap_or(0);
+        if in_bucket >= self.max_matched_subnet_in_bucket {
+            return true
+        }
+        let in_table: usize = self.buckets.values().map(|bucket| bucket.count_subnet_matches(addr)).sum();
+        in_table >= self.max_matched_subnet_in_table
+    }
+
+    /// Records a failed request to `contact`, bumping its failure counter and marking it
+    /// disconnected so that it becomes a preferred eviction candidate for its bucket. Once it
+    /// reaches `MAX_FAILURES_BEFORE_UNREACHABLE` failures it is removed from the bucket and the
+    /// newest cached replacement, if any, takes its place.
+    pub fn mark_failed(&mut self, contact: &Contact) {
+        let index = contact.log2_distance(&self.local_id);
+        if index == 0 {
+            return
+        }
+        if let Some(bucket) = self.buckets.get_mut(&index) {
+            bucket.mark_failed(contact);
+        }
+    }
+
+    /// Returns the contacts currently held in the replacement cache for the bucket at `distance`,
+    /// for test inspection.
+    #[allow(dead_code)]
+    pub fn cached_contacts(&self, distance: usize) -> Vec<Contact> {
+        self.buckets.get(&distance).map(|bucket| bucket.cached_contacts()).unwrap_or_default()
+    }
+
+    /// Promotes the most-recently-seen cached replacement for the bucket at `distance` into the
+    /// main bucket. Returns `true` if a replacement was available.
+    pub fn promote_replacement(&mut self, distance: usize) -> bool {
+        self.buckets.get_mut(&distance).map(|bucket| bucket.promote_replacement()).unwrap_or(false)
+    }
+
     #[allow(dead_code)]
     pub fn remove_contact(&mut self, contact: &Contact) -> Option<&Contact> {
         let index = contact.log2_distance(&self.local_id);
@@ -52,10 +132,9 @@ impl RoutingTable {
     }
 
     pub fn get_closest_contacts(&self, target: &NodeId, result_limit: u8) -> Vec<Contact> {
-        let contacts = self.get_contacts_in_distance_order(target);
+        let contacts = self.get_contacts_in_distance_order(target, result_limit);
         contacts
             .into_iter()
-            .take(cmp::min(result_limit, self.bucket_size) as usize)
             .map(|item| {
                 debug_assert_ne!(target, &item.contact.id());
                 debug_assert_ne!(self.local_id, item.contact.id());
@@ -64,34 +143,27 @@ impl RoutingTable {
             .collect()
     }
 
-    fn get_contacts_in_distance_order(&self, target: &NodeId) -> BTreeSet<ContactWithDistance> {
-        let mut result = BTreeSet::new();
-        let mut max_distance = 0;
-        for (_, bucket) in self.buckets.iter() {
-            for i in 0..self.bucket_size {
-                let contact = bucket.contacts.get(i as usize);
-                if contact.is_none() {
-                    break
-                }
-
-                let contact = contact.unwrap();
-
+    /// Returns up to `result_limit` contacts ordered by ascending XOR distance to `target`.
+    /// Keeps a bounded max-heap of the closest candidates seen so far, popping the farthest
+    /// whenever it overflows, so the result is always the true `k` nearest ids rather than an
+    /// arbitrary subset sharing the target's bucket.
+    fn get_contacts_in_distance_order(&self, target: &NodeId, result_limit: u8) -> Vec<ContactWithDistance> {
+        let limit = cmp::min(result_limit, self.bucket_size) as usize;
+        let mut heap: BinaryHeap<ContactWithDistance> = BinaryHeap::with_capacity(limit + 1);
+        for bucket in self.buckets.values() {
+            for entry in bucket.contacts.iter() {
+                let contact = &entry.contact;
                 if target == &contact.id() {
                     continue
                 }
 
-                let item = ContactWithDistance::new(contact, target);
-                if max_distance < item.distance {
-                    if (self.bucket_size as usize) <= result.len() {
-                        // FIXME: Remove the last item to guarantee the maximum size of return value.
-                        continue
-                    }
-                    max_distance = item.distance;
+                heap.push(ContactWithDistance::new(contact, target));
+                if heap.len() > limit {
+                    heap.pop();
                 }
-                result.insert(item);
             }
         }
-        result
+        heap.into_sorted_vec()
     }
 
     pub fn contains(&self, contact: &Contact) -> bool {
@@ -129,7 +201,10 @@ impl RoutingTable {
     }
 
     pub fn get_contacts_with_distance(&self, distance: usize) -> Vec<Contact> {
-        self.buckets.get(&distance).map(|bucket| Vec::from(bucket.contacts.clone())).unwrap_or(vec![])
+        self.buckets
+            .get(&distance)
+            .map(|bucket| bucket.contacts.iter().map(|entry| entry.contact.clone()).collect())
+            .unwrap_or(vec![])
     }
 
     pub fn remove_address(&mut self, address: &SocketAddr) {
@@ -141,11 +216,103 @@ impl RoutingTable {
     pub fn len(&self) -> usize {
         self.buckets.values().map(|bucket| bucket.contacts.len()).sum()
     }
+
+    /// Samples up to `per_bucket` random contacts from every non-empty bucket, for the periodic
+    /// "ping a random node per bucket" liveness probe that keeps the table fresh.
+    pub fn random_contacts_for_refresh(&self, per_bucket: usize) -> Vec<Contact> {
+        let mut rng = thread_rng();
+        let mut result = Vec::new();
+        for bucket in self.buckets.values() {
+            let mut contacts: Vec<&Contact> = bucket.contacts.iter().map(|entry| &entry.contact).collect();
+            contacts.shuffle(&mut rng);
+            result.extend(contacts.into_iter().take(per_bucket).cloned());
+        }
+        result
+    }
+
+    /// Returns the distance of every bucket whose most-recently-touched contact was last seen
+    /// longer than `stale_after` ago, so the discovery layer can drive `FIND_NODE` refreshes at
+    /// exactly the buckets that need it.
+    pub fn buckets_needing_refresh(&self, stale_after: Duration) -> Vec<usize> {
+        let now = Instant::now();
+        self.buckets
+            .iter()
+            .filter_map(|(distance, bucket)| {
+                let most_recent = bucket.contacts.iter().map(|entry| entry.last_seen).max()?;
+                if now.duration_since(most_recent) >= stale_after {
+                    Some(*distance)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
 }
 
 
+/// Liveness state of a contact inside a bucket, mirroring karyon's routing table status model.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct ContactStatus(u8);
+
+impl ContactStatus {
+    const CONNECTED: ContactStatus = ContactStatus(0b0_0001);
+    const DISCONNECTED: ContactStatus = ContactStatus(0b0_0010);
+    #[allow(dead_code)]
+    const PENDING: ContactStatus = ContactStatus(0b0_0100);
+    const UNREACHABLE: ContactStatus = ContactStatus(0b0_1000);
+    #[allow(dead_code)]
+    const UNSTABLE: ContactStatus = ContactStatus(0b1_0000);
+
+    fn intersects(self, other: ContactStatus) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+/// A contact failing this many consecutive requests is considered unreachable rather than
+/// merely disconnected, and is retired from its bucket.
+const MAX_FAILURES_BEFORE_UNREACHABLE: u32 = 3;
+
+struct BucketEntry {
+    contact: Contact,
+    status: ContactStatus,
+    failures: u32,
+    last_seen: Instant,
+}
+
+impl BucketEntry {
+    fn new(contact: Contact) -> Self {
+        BucketEntry {
+            contact,
+            status: ContactStatus::CONNECTED,
+            failures: 0,
+            last_seen: Instant::now(),
+        }
+    }
+
+    fn is_dead(&self) -> bool {
+        self.status.intersects(ContactStatus::DISCONNECTED | ContactStatus::UNREACHABLE)
+    }
+
+    /// Higher is a better eviction candidate: dead status first, then failure count, then
+    /// staleness, so `Iterator::max_by_key` picks the worst node.
+    fn eviction_rank(&self) -> (bool, u32, cmp::Reverse<Instant>) {
+        (self.is_dead(), self.failures, cmp::Reverse(self.last_seen))
+    }
+}
+
+impl ::std::ops::BitOr for ContactStatus {
+    type Output = ContactStatus;
+
+    fn bitor(self, rhs: ContactStatus) -> ContactStatus {
+        ContactStatus(self.0 | rhs.0)
+    }
+}
+
 struct Bucket {
-    contacts: VecDeque<Contact>,
+    contacts: VecDeque<BucketEntry>,
+    /// Overflow candidates bumped from a full bucket, kept around so a churned-out dead entry
+    /// can be backfilled immediately instead of waiting on a fresh lookup.
+    replacements: VecDeque<Contact>,
     bucket_size: u8,
 }
 
@@ -153,63 +320,150 @@ impl Bucket {
     pub fn new(bucket_size: u8) -> Self {
         Bucket {
             contacts: VecDeque::new(),
+            replacements: VecDeque::new(),
             bucket_size,
         }
     }
 
-    pub fn touch_contact(&mut self, contact: Contact) -> Option<&Contact> {
-        self.remove_contact(&contact);
-        if !self.conflicts(&contact) {
-            self.contacts.push_back(contact);
+    pub fn touch_contact(&mut self, contact: Contact) -> AddEntryResult {
+        if self.contains(&contact) {
+            self.remove_contact(&contact);
+            self.contacts.push_back(BucketEntry::new(contact));
+            return AddEntryResult::Exists
+        }
+        if self.conflicts(&contact) {
+            return AddEntryResult::Restricted
+        }
+        if let Some(worst) = self.head_if_full() {
+            let worst = worst.clone();
+            self.cache_replacement(contact);
+            return AddEntryResult::BucketFull(worst)
+        }
+        self.contacts.push_back(BucketEntry::new(contact));
+        AddEntryResult::Added
+    }
+
+    /// Records a failed request to `contact`: bumps its failure counter and flips its status to
+    /// `DISCONNECTED`, or `UNREACHABLE` once it has failed too many times in a row. A contact
+    /// that just crossed into `UNREACHABLE` is retired from the bucket and immediately replaced
+    /// with the most-recently-seen cached candidate, if any.
+    pub fn mark_failed(&mut self, contact: &Contact) {
+        let mut retired = false;
+        if let Some(entry) = self.contacts.iter_mut().find(|entry| &entry.contact == contact) {
+            entry.failures += 1;
+            entry.status = if entry.failures >= MAX_FAILURES_BEFORE_UNREACHABLE {
+                ContactStatus::UNREACHABLE
+            } else {
+                ContactStatus::DISCONNECTED
+            };
+            // An entry is retired the moment it reaches `UNREACHABLE`, so it can never already be
+            // in that state here; `retired` is simply "did this failure just cross the threshold".
+            retired = entry.status == ContactStatus::UNREACHABLE;
+        }
+        if retired {
+            self.contacts.retain(|entry| &entry.contact != contact);
+            self.promote_replacement();
         }
-        self.head_if_full()
     }
 
-
     pub fn remove_contact(&mut self, contact: &Contact) -> Option<&Contact> {
-        self.contacts.retain(|old_contact| old_contact != contact);
+        self.contacts.retain(|entry| &entry.contact != contact);
         self.head_if_full()
     }
 
+    /// When the bucket is at capacity, returns the worst candidate for eviction instead of
+    /// simply the oldest entry, so the caller can ping it before replacing it.
     fn head_if_full(&self) -> Option<&Contact> {
-        if self.contacts.len() > self.bucket_size as usize {
-            self.contacts.front()
+        if self.contacts.len() >= self.bucket_size as usize {
+            self.contacts.iter().max_by_key(|entry| entry.eviction_rank()).map(|entry| &entry.contact)
         } else {
             None
         }
     }
 
+    /// Remembers `contact` as a fallback for this bucket, evicting the oldest cached candidate
+    /// once the replacement cache itself is full.
+    fn cache_replacement(&mut self, contact: Contact) {
+        self.replacements.retain(|cached| cached != &contact);
+        if self.replacements.len() >= self.bucket_size as usize {
+            self.replacements.pop_front();
+        }
+        self.replacements.push_back(contact);
+    }
+
+    /// Promotes the most-recently-seen cached replacement into the main bucket. Returns `true`
+    /// if a replacement was available.
+    fn promote_replacement(&mut self) -> bool {
+        match self.replacements.pop_back() {
+            Some(contact) => {
+                self.contacts.push_back(BucketEntry::new(contact));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn cached_contacts(&self) -> Vec<Contact> {
+        Vec::from(self.replacements.clone())
+    }
+
     pub fn is_empty(&self) -> bool {
         self.contacts.is_empty()
     }
 
     fn contains(&self, contact: &Contact) -> bool {
-        self.contacts.contains(contact)
+        self.contacts.iter().any(|entry| &entry.contact == contact)
     }
 
     pub fn conflicts(&self, contact: &Contact) -> bool {
         self.contacts
             .iter()
-            .find(|old_contact| old_contact.id() == contact.id() && old_contact.addr() != contact.addr())
+            .find(|entry| entry.contact.id() == contact.id() && entry.contact.addr() != contact.addr())
             .is_some()
     }
 
     fn remove_address(&mut self, address: &SocketAddr) {
-        self.contacts.retain(|contact| contact.addr() != address);
+        self.contacts.retain(|entry| entry.contact.addr() != address);
+    }
+
+    fn count_subnet_matches(&self, addr: &SocketAddr) -> usize {
+        self.contacts.iter().filter(|entry| subnet_match(entry.contact.addr(), addr)).count()
     }
 }
 
+/// True if `a` and `b` fall in the same IPv4 /24 or IPv6 /64, the granularity karyon uses to
+/// detect an attacker flooding the table from a single subnet.
+fn subnet_match(a: &SocketAddr, b: &SocketAddr) -> bool {
+    match (a.ip(), b.ip()) {
+        (IpAddr::V4(a), IpAddr::V4(b)) => a.octets()[0..3] == b.octets()[0..3],
+        (IpAddr::V6(a), IpAddr::V6(b)) => a.octets()[0..8] == b.octets()[0..8],
+        _ => false,
+    }
+}
+
+
+/// The XOR distance between two node ids as a big-endian byte array, so ordering it compares the
+/// full 256 bits rather than just the bucket index (`log2_distance`).
+fn xor_distance(a: &NodeId, b: &NodeId) -> [u8; 32] {
+    let a = a.as_ref();
+    let b = b.as_ref();
+    let mut distance = [0u8; 32];
+    for (byte, (a, b)) in distance.iter_mut().zip(a.iter().zip(b.iter())) {
+        *byte = a ^ b;
+    }
+    distance
+}
 
 #[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
 struct ContactWithDistance {
-    distance: usize,
+    distance: [u8; 32],
     contact: Contact,
 }
 
 impl ContactWithDistance {
     pub fn new(contact: &Contact, target: &NodeId) -> Self {
         ContactWithDistance {
-            distance: contact.log2_distance(&target),
+            distance: xor_distance(&contact.id(), target),
             contact: contact.clone(),
         }
     }
@@ -218,8 +472,10 @@ impl ContactWithDistance {
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use super::super::contact::Contact;
-    use super::RoutingTable;
+    use super::{subnet_match, AddEntryResult, RoutingTable, MAX_FAILURES_BEFORE_UNREACHABLE};
 
     const IDS: [&str; 18] = [
         "0000000000000000000000000000000000000000000000000000000000000000",
@@ -295,9 +551,9 @@ mod tests {
         assert_eq!(BUCKET_SIZE as usize, closest_contacts.len());
         assert_eq!(get_contact(2), closest_contacts[0]);
         assert_eq!(get_contact(1), closest_contacts[1]);
-        assert_eq!(get_contact(4), closest_contacts[2]);
-        assert_eq!(get_contact(5), closest_contacts[3]);
-        assert_eq!(get_contact(6), closest_contacts[4]);
+        assert_eq!(get_contact(7), closest_contacts[2]);
+        assert_eq!(get_contact(6), closest_contacts[3]);
+        assert_eq!(get_contact(5), closest_contacts[4]);
     }
 
     #[test]
@@ -400,7 +656,211 @@ mod tests {
         assert_eq!(1, routing_table.get_contacts_with_distance(1).len());
         assert_eq!(2, routing_table.get_contacts_with_distance(2).len());
         assert_eq!(4, routing_table.get_contacts_with_distance(3).len());
-        assert_eq!(8, routing_table.get_contacts_with_distance(4).len());
+        // Distance 4 has 8 candidates (node ids 8..=15) but the bucket only holds BUCKET_SIZE;
+        // the rest overflow into the replacement cache instead of growing the bucket unbounded.
+        assert_eq!(BUCKET_SIZE as usize, routing_table.get_contacts_with_distance(4).len());
+        assert_eq!(3, routing_table.cached_contacts(4).len());
         assert_eq!(2, routing_table.get_contacts_with_distance(5).len());
     }
+
+    #[test]
+    fn test_head_if_full_prefers_failed_contact_over_oldest() {
+        const BUCKET_SIZE: u8 = 2;
+        let local_id = get_contact(0).id();
+        let mut routing_table = RoutingTable::new(local_id, BUCKET_SIZE);
+
+        routing_table.touch_contact(get_contact(8));
+        routing_table.touch_contact(get_contact(9));
+        routing_table.mark_failed(&get_contact(9));
+
+        // Node 8 is the oldest entry and would be evicted under plain FIFO, but node 9 has
+        // already failed a request, so it should be preferred for eviction instead.
+        let evicted = routing_table.touch_contact(get_contact(10));
+        assert_eq!(AddEntryResult::BucketFull(get_contact(9)), evicted);
+    }
+
+    #[test]
+    fn test_head_if_full_prefers_more_failures_over_healthy_and_stale() {
+        const BUCKET_SIZE: u8 = 3;
+        let local_id = get_contact(0).id();
+        let mut routing_table = RoutingTable::new(local_id, BUCKET_SIZE);
+
+        routing_table.touch_contact(get_contact(8)); // oldest, stays healthy
+        routing_table.touch_contact(get_contact(9)); // fails once
+        routing_table.touch_contact(get_contact(10)); // newest, fails twice
+        routing_table.mark_failed(&get_contact(9));
+        routing_table.mark_failed(&get_contact(10));
+        routing_table.mark_failed(&get_contact(10));
+
+        // Node 8 is the oldest entry but is still healthy, so `is_dead` rules it out first.
+        // Between the two dead entries, node 10 has failed more times than node 9 even though
+        // it's the newer of the two, so `failures` breaks the tie ahead of staleness.
+        let evicted = routing_table.touch_contact(get_contact(11));
+        assert_eq!(AddEntryResult::BucketFull(get_contact(10)), evicted);
+    }
+
+    #[test]
+    fn test_touch_contact_rejects_second_contact_from_same_subnet_in_bucket() {
+        const BUCKET_SIZE: u8 = 5;
+        let local_id = get_contact(0).id();
+        let mut routing_table = RoutingTable::new(local_id, BUCKET_SIZE);
+
+        let first = get_contact_with_address(8, 127, 0, 0, 1, 3000);
+        let second = get_contact_with_address(9, 127, 0, 0, 2, 3001);
+
+        routing_table.touch_contact(first.clone());
+        routing_table.touch_contact(second.clone());
+
+        assert!(routing_table.contains(&first));
+        assert!(!routing_table.contains(&second));
+    }
+
+    #[test]
+    fn test_subnet_match_checks_ipv4_slash_24() {
+        let a = get_contact_with_address(1, 127, 0, 0, 1, 3000);
+        let b = get_contact_with_address(2, 127, 0, 0, 2, 3001);
+        let c = get_contact_with_address(3, 127, 0, 1, 1, 3002);
+
+        assert!(subnet_match(a.addr(), b.addr()));
+        assert!(!subnet_match(a.addr(), c.addr()));
+    }
+
+    #[test]
+    fn test_touch_contact_returns_ignored_for_self() {
+        const BUCKET_SIZE: u8 = 5;
+        let local_id = get_contact(0).id();
+        let mut routing_table = RoutingTable::new(local_id, BUCKET_SIZE);
+
+        assert_eq!(AddEntryResult::Ignored, routing_table.touch_contact(get_contact(0)));
+    }
+
+    #[test]
+    fn test_touch_contact_returns_exists_for_duplicate() {
+        const BUCKET_SIZE: u8 = 5;
+        let local_id = get_contact(0).id();
+        let mut routing_table = RoutingTable::new(local_id, BUCKET_SIZE);
+
+        routing_table.touch_contact(get_contact(1));
+        assert_eq!(AddEntryResult::Exists, routing_table.touch_contact(get_contact(1)));
+    }
+
+    #[test]
+    fn test_touch_contact_returns_restricted_on_id_conflict() {
+        const BUCKET_SIZE: u8 = 5;
+        let local_id = get_contact(0).id();
+        let mut routing_table = RoutingTable::new(local_id, BUCKET_SIZE);
+
+        routing_table.touch_contact(get_contact(4));
+        let conflicting = get_contact_with_address(4, 127, 0, 0, 1, 3485);
+        assert_eq!(AddEntryResult::Restricted, routing_table.touch_contact(conflicting));
+    }
+
+    #[test]
+    fn test_touch_contact_returns_bucket_full_with_eviction_candidate() {
+        const BUCKET_SIZE: u8 = 2;
+        let local_id = get_contact(0).id();
+        let mut routing_table = RoutingTable::new(local_id, BUCKET_SIZE);
+
+        routing_table.touch_contact(get_contact(8));
+        routing_table.touch_contact(get_contact(9));
+
+        assert_eq!(AddEntryResult::BucketFull(get_contact(8)), routing_table.touch_contact(get_contact(10)));
+    }
+
+    #[test]
+    fn test_get_closest_contacts_orders_by_true_xor_distance_not_log2_bucket() {
+        const BUCKET_SIZE: u8 = 18;
+        let local_id = get_contact(0).id();
+        let mut routing_table = RoutingTable::new(local_id, BUCKET_SIZE);
+
+        for i in &[8usize, 9, 10, 12, 13, 14, 15] {
+            routing_table.touch_contact(get_contact(*i));
+        }
+
+        // 12..15 all share the same log2 bucket relative to target 11, but their true XOR
+        // distances (7, 6, 5, 4) are in the opposite order of their node ids.
+        let closest = routing_table.get_closest_contacts(&get_contact(11).id(), BUCKET_SIZE);
+        let expected: Vec<Contact> = [10, 9, 8, 15, 14, 13, 12].iter().map(|&i| get_contact(i)).collect();
+        assert_eq!(expected, closest);
+    }
+
+    #[test]
+    fn test_random_contacts_for_refresh_caps_per_bucket() {
+        const BUCKET_SIZE: u8 = 18;
+        let local_id = get_contact(0).id();
+        let mut routing_table = RoutingTable::new(local_id, BUCKET_SIZE);
+
+        for i in 1..IDS.len() {
+            routing_table.touch_contact(get_contact(i));
+        }
+
+        let sample = routing_table.random_contacts_for_refresh(1);
+        // Every populated bucket contributes at most one contact.
+        assert_eq!(routing_table.distances().len(), sample.len());
+        for contact in &sample {
+            assert!(routing_table.contains(contact));
+        }
+    }
+
+    #[test]
+    fn test_buckets_needing_refresh_flags_stale_buckets() {
+        const BUCKET_SIZE: u8 = 18;
+        let local_id = get_contact(0).id();
+        let mut routing_table = RoutingTable::new(local_id, BUCKET_SIZE);
+
+        routing_table.touch_contact(get_contact(1));
+        routing_table.touch_contact(get_contact(8));
+
+        let immediately_stale = routing_table.buckets_needing_refresh(Duration::from_secs(0));
+        assert_eq!(routing_table.distances().len(), immediately_stale.len());
+
+        let never_stale = routing_table.buckets_needing_refresh(Duration::from_secs(3600));
+        assert!(never_stale.is_empty());
+    }
+
+    #[test]
+    fn test_touch_contact_caches_overflow_as_replacement() {
+        const BUCKET_SIZE: u8 = 2;
+        let local_id = get_contact(0).id();
+        let mut routing_table = RoutingTable::new(local_id, BUCKET_SIZE);
+
+        routing_table.touch_contact(get_contact(8));
+        routing_table.touch_contact(get_contact(9));
+        routing_table.touch_contact(get_contact(10));
+
+        assert_eq!(vec![get_contact(10)], routing_table.cached_contacts(4));
+    }
+
+    #[test]
+    fn test_mark_failed_retiring_a_contact_promotes_newest_replacement() {
+        const BUCKET_SIZE: u8 = 2;
+        let local_id = get_contact(0).id();
+        let mut routing_table = RoutingTable::new(local_id, BUCKET_SIZE);
+
+        routing_table.touch_contact(get_contact(8));
+        routing_table.touch_contact(get_contact(9));
+        routing_table.touch_contact(get_contact(10));
+        routing_table.touch_contact(get_contact(11));
+
+        for _ in 0..MAX_FAILURES_BEFORE_UNREACHABLE {
+            routing_table.mark_failed(&get_contact(8));
+        }
+
+        // Node 8 crossed into UNREACHABLE and was retired, so the most recently cached
+        // replacement (node 11) should have been promoted into the main bucket automatically.
+        assert!(!routing_table.contains(&get_contact(8)));
+        assert!(routing_table.contains(&get_contact(11)));
+        assert_eq!(vec![get_contact(10)], routing_table.cached_contacts(4));
+    }
+
+    #[test]
+    fn test_promote_replacement_returns_false_when_cache_empty() {
+        const BUCKET_SIZE: u8 = 5;
+        let local_id = get_contact(0).id();
+        let mut routing_table = RoutingTable::new(local_id, BUCKET_SIZE);
+
+        routing_table.touch_contact(get_contact(8));
+
+        assert!(!routing_table.promote_replacement(4));
+    }
 }